@@ -0,0 +1,91 @@
+use std::str::FromStr;
+
+/// A window height/width value, e.g. `800` (fixed) or `50%` (proportion of
+/// the output/column).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PresetSize {
+    Fixed(i32),
+    Proportion(f64),
+}
+
+impl FromStr for PresetSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_suffix('%') {
+            Some(pct) => pct
+                .parse::<f64>()
+                .map(|v| PresetSize::Proportion(v / 100.0))
+                .map_err(|e| format!("invalid percentage {s:?}: {e}")),
+            None => s
+                .parse::<i32>()
+                .map(PresetSize::Fixed)
+                .map_err(|e| format!("invalid fixed size {s:?}: {e}")),
+        }
+    }
+}
+
+impl From<PresetSize> for niri_ipc::SizeChange {
+    fn from(p: PresetSize) -> Self {
+        match p {
+            PresetSize::Fixed(v) => niri_ipc::SizeChange::SetFixed(v),
+            PresetSize::Proportion(v) => niri_ipc::SizeChange::SetProportion(v * 100.0),
+        }
+    }
+}
+
+/// A `height`/`width` consequence's argument, e.g. `800` or `50%`.
+///
+/// Wraps `Option` because knuffel still wants something to decode when the
+/// node is present but empty (`height`/`width` with no argument), which niri
+/// treats as "use the compositor default" - i.e. send no action at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DefaultPresetSize(pub Option<PresetSize>);
+
+impl<S: knuffel::traits::ErrorSpan> knuffel::Decode<S> for DefaultPresetSize {
+    fn decode_node(
+        node: &knuffel::ast::SpannedNode<S>,
+        ctx: &mut knuffel::decode::Context<S>,
+    ) -> Result<Self, knuffel::errors::DecodeError<S>> {
+        let mut args = node.arguments.iter();
+        let value = match args.next() {
+            None => None,
+            Some(val) => {
+                Some(
+                    knuffel::traits::DecodeScalar::decode(val, ctx).and_then(|s: String| {
+                        s.parse::<PresetSize>()
+                            .map_err(|e| knuffel::errors::DecodeError::conversion(&val.literal, e))
+                    })?,
+                )
+            }
+        };
+        for val in args {
+            ctx.emit_error(knuffel::errors::DecodeError::unexpected(
+                &val.literal,
+                "argument",
+                "unexpected extra argument",
+            ));
+        }
+        Ok(DefaultPresetSize(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_size_parses_fixed_and_percentage() {
+        assert_eq!("800".parse::<PresetSize>().unwrap(), PresetSize::Fixed(800));
+        assert_eq!(
+            "50%".parse::<PresetSize>().unwrap(),
+            PresetSize::Proportion(0.5)
+        );
+    }
+
+    #[test]
+    fn preset_size_rejects_garbage() {
+        assert!("not-a-size".parse::<PresetSize>().is_err());
+        assert!("%".parse::<PresetSize>().is_err());
+    }
+}
@@ -1,30 +1,45 @@
 mod kdl_utils;
+mod supervisor;
 mod window_rule;
 
-use std::collections::HashSet;
-use std::convert::identity;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 use std::{env, fs};
 
 use clap::Parser;
+use crossbeam_channel::{select, Receiver};
+use log::{debug, info, trace, warn};
 use miette::{Context, IntoDiagnostic};
 use niri_ipc::socket::Socket;
-use niri_ipc::{Action, Event, Request, Response, Window};
+use niri_ipc::{Action, Event, Request, Response, Window, Workspace, WorkspaceReferenceArg};
+use notify::{RecursiveMode, Watcher};
 
-use window_rule::{Match, WindowRule, WindowRules};
+use supervisor::Supervisor;
+use window_rule::{Condition, Consequence, WindowRule, WindowRules};
 
-use crate::kdl_utils::DefaultPresetSize;
-
-type WindowId = u64;
+pub(crate) type WindowId = u64;
 
 #[derive(Parser)]
 #[command(about = "Limited generic niri event handler?", long_about = None)]
 struct Cli {
     #[arg(short, long, value_name = "FILE")]
     rules: Option<String>,
+    // Run the matching pipeline and log what it would send to niri, without
+    // actually sending it or spawning anything.
+    #[arg(long)]
+    dry_run: bool,
 }
 
 fn main() -> miette::Result<()> {
+    env_logger::init();
+
     let cli = Cli::parse();
+    let dry_run = cli.dry_run;
+    if dry_run {
+        info!("--dry-run: matching pipeline will run but no actions will be sent or spawned");
+    }
     let rules = match cli.rules {
         Some(rules) => rules,
         None => {
@@ -36,65 +51,322 @@ fn main() -> miette::Result<()> {
             conf_home + "/niri/dyn_rules.kdl"
         }
     };
-    let windowrules = parse_config(&rules)?.windowrules;
+    let mut windowrules = parse_config(&rules)?.windowrules;
 
-    let mut listening_socket = Socket::connect().into_diagnostic()?;
+    let listening_socket = connect_and_subscribe()?;
     let mut sending_socket = Socket::connect().into_diagnostic()?;
-    let mut matched_windows: Vec<HashSet<WindowId>> = Vec::with_capacity(windowrules.len());
-    for _ in &windowrules {
-        matched_windows.push(HashSet::new());
-    }
+    let mut matched_windows = build_matched_windows(&windowrules);
+    let mut workspaces: HashMap<u64, Workspace> = HashMap::new();
+    let mut supervisor = Supervisor::default();
 
-    handle_send(Request::EventStream, &mut listening_socket)?;
-
-    let mut read_event = listening_socket.read_events();
-    while let Ok(event) = read_event() {
-        match event {
-            Event::WindowsChanged { windows } => {
-                for window in windows {
-                    handle_window(
-                        window,
-                        &windowrules,
-                        &mut matched_windows,
-                        &mut sending_socket,
-                    )?;
+    let niri_events = spawn_event_reader(listening_socket);
+    // Kept alive for the life of the process - dropping it stops delivery.
+    let (_watcher, reloads) = watch_rules_file(&rules)?;
+    let supervisor_ticks = crossbeam_channel::tick(Duration::from_millis(250));
+    let shutdown_signals = spawn_shutdown_signal()?;
+
+    loop {
+        select! {
+            recv(niri_events) -> msg => {
+                let Ok(msg) = msg else {
+                    // reader thread panicked, which it otherwise never does -
+                    // it retries forever on its own
+                    break;
+                };
+                let event = match msg {
+                    NiriMsg::Reconnected => {
+                        // Niri resends a full workspace snapshot on
+                        // resubscribe, so `workspaces` needs rebuilding from
+                        // it - but `matched_windows` is our own bookkeeping
+                        // of which rules already fired for which windows, not
+                        // connection state, so it must survive a reconnect
+                        // untouched or every matched window's consequences
+                        // (including `close` and `spawn-sh`) would refire.
+                        info!("reconnected to niri, resetting workspace state");
+                        workspaces.clear();
+                        continue;
+                    }
+                    NiriMsg::Event(event) => event,
+                };
+                match event {
+                    Event::WindowsChanged { windows } => {
+                        for window in windows {
+                            handle_window(
+                                window,
+                                &windowrules,
+                                &mut matched_windows,
+                                &workspaces,
+                                &mut sending_socket,
+                                &mut supervisor,
+                                dry_run,
+                            )?;
+                        }
+                    }
+                    Event::WindowOpenedOrChanged { window } => {
+                        handle_window(
+                            window,
+                            &windowrules,
+                            &mut matched_windows,
+                            &workspaces,
+                            &mut sending_socket,
+                            &mut supervisor,
+                            dry_run,
+                        )?;
+                    }
+                    Event::WindowClosed { id } => {
+                        for matched in matched_windows.values_mut() {
+                            matched.remove(&id);
+                        }
+                        supervisor.stop_for_window(id);
+                    }
+                    Event::WorkspacesChanged { workspaces: all } => {
+                        workspaces = all.into_iter().map(|w| (w.id, w)).collect();
+                    }
+                    Event::WorkspaceCreated { workspace } => {
+                        workspaces.insert(workspace.id, workspace);
+                    }
+                    Event::WorkspaceRemoved { id } => {
+                        workspaces.remove(&id);
+                    }
+                    Event::WorkspaceActivated { id, focused } => {
+                        // "workspace-switched": the activated workspace becomes
+                        // the focused one on its output; the rest just lose
+                        // that status.
+                        if focused {
+                            for ws in workspaces.values_mut() {
+                                ws.is_focused = ws.id == id;
+                            }
+                        }
+                        // Likewise it becomes the *active* workspace on its
+                        // own output - every other workspace sharing that
+                        // output stops being active, same as niri itself only
+                        // ever has one active workspace per output.
+                        if let Some(output) = workspaces.get(&id).and_then(|ws| ws.output.clone()) {
+                            for ws in workspaces.values_mut() {
+                                if ws.output.as_ref() == Some(&output) {
+                                    ws.is_active = ws.id == id;
+                                }
+                            }
+                        }
+                    }
+                    Event::WorkspaceActiveWindowChanged { .. } => (),
+                    _ => (),
+                }
+            }
+            recv(reloads) -> signal => {
+                if signal.is_err() {
+                    // watcher thread died, nothing more will ever arrive on it
+                    break;
+                }
+                match parse_config(&rules) {
+                    Ok(new_rules) => {
+                        matched_windows =
+                            rebuild_matched_windows(matched_windows, &new_rules.windowrules);
+                        windowrules = new_rules.windowrules;
+                    }
+                    Err(err) => {
+                        warn!("could not reload {rules:?}, keeping previous rules: {err:?}")
+                    }
                 }
             }
-            Event::WindowOpenedOrChanged { window } => {
-                handle_window(
-                    window,
-                    &windowrules,
-                    &mut matched_windows,
-                    &mut sending_socket,
-                )?;
+            recv(supervisor_ticks) -> _ => supervisor.poll(),
+            recv(shutdown_signals) -> _ => {
+                info!("shutting down, stopping supervised processes");
+                supervisor.shutdown();
+                break;
             }
-            Event::WindowClosed { id } => drop(matched_windows.iter_mut().map(|x| x.remove(&id))),
-            _ => (),
         }
     }
 
     Ok(())
 }
 
+fn connect_and_subscribe() -> miette::Result<Socket> {
+    let mut socket = Socket::connect().into_diagnostic()?;
+    handle_send(Request::EventStream, &mut socket)?;
+    Ok(socket)
+}
+
+enum NiriMsg {
+    Event(Event),
+    Reconnected,
+}
+
+/// Spawns the thread draining niri's blocking event stream and forwards each
+/// event over a channel so the main loop can `select!` on it alongside the
+/// rules-file watcher instead of blocking on the socket read alone. On a read
+/// error (e.g. niri restarting) it reconnects with a capped exponential
+/// backoff and re-subscribes instead of giving up, sending `Reconnected` so
+/// the main loop knows to rebuild state that only a live connection can
+/// repopulate (the workspace map; `matched_windows` is kept as it is).
+fn spawn_event_reader(initial: Socket) -> Receiver<NiriMsg> {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    thread::spawn(move || {
+        let mut socket = initial;
+        loop {
+            let mut read_event = socket.read_events();
+            loop {
+                match read_event() {
+                    Ok(event) => {
+                        if tx.send(NiriMsg::Event(event)).is_err() {
+                            return; // main loop is gone, nothing left to do
+                        }
+                    }
+                    Err(err) => {
+                        warn!("lost the niri event stream ({err}), reconnecting");
+                        break;
+                    }
+                }
+            }
+
+            let mut backoff = Duration::from_millis(250);
+            loop {
+                match connect_and_subscribe() {
+                    Ok(reconnected) => {
+                        socket = reconnected;
+                        if tx.send(NiriMsg::Reconnected).is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                    Err(err) => {
+                        warn!("failed to reconnect to niri ({err}), retrying in {backoff:?}");
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(Duration::from_secs(30));
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Spawns the thread that turns SIGINT/SIGTERM into a single message on the
+/// returned channel so shutdown goes through the same `select!` loop as
+/// everything else instead of an abrupt process kill.
+fn spawn_shutdown_signal() -> miette::Result<Receiver<()>> {
+    let (tx, rx) = crossbeam_channel::bounded(1);
+    let mut signals = signal_hook::iterator::Signals::new([
+        signal_hook::consts::SIGINT,
+        signal_hook::consts::SIGTERM,
+    ])
+    .into_diagnostic()
+    .wrap_err("could not install a SIGINT/SIGTERM handler")?;
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            let _ = tx.send(());
+        }
+    });
+    Ok(rx)
+}
+
+/// Watches the rules file for modifications and sends a signal (content is
+/// irrelevant - the receiver just re-reads the file) each time it changes.
+/// The returned watcher must be kept alive for as long as `rx` is polled.
+///
+/// Watches the file's parent directory rather than the file itself: editors
+/// that save atomically (write a temp file, then rename it over the
+/// original, which is vim's default and common elsewhere) replace the
+/// file's inode, and a watch on that inode dies silently on the first such
+/// save, leaving every later edit unnoticed.
+fn watch_rules_file(path: &str) -> miette::Result<(notify::RecommendedWatcher, Receiver<()>)> {
+    let path = Path::new(path);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| miette::miette!("rule file path {path:?} has no file name"))?
+        .to_owned();
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    let watch_target = dir.unwrap_or_else(|| Path::new("."));
+
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let relevant = matches!(&res, Ok(event) if (event.kind.is_modify() || event.kind.is_create())
+            && event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())));
+        if relevant {
+            let _ = tx.send(());
+        }
+    })
+    .into_diagnostic()
+    .wrap_err("could not start a watcher on the rule file")?;
+
+    watcher
+        .watch(watch_target, RecursiveMode::NonRecursive)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("could not watch directory {watch_target:?} for {path:?}"))?;
+
+    Ok((watcher, rx))
+}
+
+fn build_matched_windows(windowrules: &[WindowRule]) -> HashMap<String, HashSet<WindowId>> {
+    windowrules
+        .iter()
+        .enumerate()
+        .map(|(idx, wr)| (wr.label_or_index(idx), HashSet::new()))
+        .collect()
+}
+
+/// Rebuilds `matched_windows` for a freshly-reloaded rule set, carrying over
+/// the windows already matched by rules whose label didn't change so an
+/// edited config doesn't re-fire consequences for windows it already applied
+/// to.
+fn rebuild_matched_windows(
+    mut old: HashMap<String, HashSet<WindowId>>,
+    windowrules: &[WindowRule],
+) -> HashMap<String, HashSet<WindowId>> {
+    windowrules
+        .iter()
+        .enumerate()
+        .map(|(idx, wr)| {
+            let label = wr.label_or_index(idx);
+            let matched = old.remove(&label).unwrap_or_default();
+            (label, matched)
+        })
+        .collect()
+}
+
 fn parse_config(path: &str) -> miette::Result<WindowRules> {
     let text = fs::read_to_string(path)
         .into_diagnostic()
         .wrap_err_with(|| format!("could not read rule file {:?}", path))?;
-    Ok(knuffel::parse(path, &text)?)
+    let rules: WindowRules = knuffel::parse(path, &text)?;
+    check_unique_labels(&rules.windowrules)
+        .wrap_err_with(|| format!("invalid rule file {:?}", path))?;
+    Ok(rules)
+}
+
+/// `matched_windows` is keyed by label, so two rules resolving to the same
+/// label (an explicit duplicate, or an unlabeled rule whose fallback index
+/// collides with another rule's explicit label) would silently share one
+/// fire-once bucket - whichever rule matches a window first would "claim"
+/// it, and the other rule's consequences would just never fire, with no
+/// error. Reject that at parse/reload time instead.
+fn check_unique_labels(windowrules: &[WindowRule]) -> miette::Result<()> {
+    let mut seen = HashSet::new();
+    for (idx, wr) in windowrules.iter().enumerate() {
+        let label = wr.label_or_index(idx);
+        if !seen.insert(label.clone()) {
+            return Err(miette::miette!(
+                "duplicate window-rule label {label:?} - labels (explicit or falling back to the rule's position) must be unique"
+            ));
+        }
+    }
+    Ok(())
 }
 
 fn handle_window(
     window: Window,
     windowrules: &[WindowRule],
-    matched_windows: &mut [HashSet<WindowId>],
+    matched_windows: &mut HashMap<String, HashSet<WindowId>>,
+    workspaces: &HashMap<u64, Workspace>,
     socket: &mut Socket,
+    supervisor: &mut Supervisor,
+    dry_run: bool,
 ) -> miette::Result<()> {
-    let rules_that_apply = rules_that_apply(&window, windowrules);
+    let rules_that_apply = rules_that_apply(&window, windowrules, workspaces);
 
-    for (rule_idx, wr) in rules_that_apply {
-        if matched_windows[rule_idx].insert(window.id) {
-            take_windowrule_actions(&window, wr, socket)?;
-        }
+    for (label, wr) in rules_that_apply {
+        let first_match = matched_windows.entry(label.clone()).or_default().insert(window.id);
+        take_windowrule_actions(&window, wr, &label, first_match, socket, supervisor, dry_run)?;
     }
 
     Ok(())
@@ -103,50 +375,103 @@ fn handle_window(
 fn rules_that_apply<'a>(
     window: &Window,
     windowrules: &'a [WindowRule],
-) -> Vec<(usize, &'a WindowRule)> {
-    windowrules
+    workspaces: &HashMap<u64, Workspace>,
+) -> Vec<(String, &'a WindowRule)> {
+    let matched: Vec<(String, &WindowRule)> = windowrules
         .iter()
         .enumerate()
-        .filter(|(_, wr)| rule_applies(window, wr))
-        .collect()
+        .filter(|(_, wr)| rule_applies(window, wr, workspaces))
+        .map(|(idx, wr)| (wr.label_or_index(idx), wr))
+        .collect();
+
+    if matched.is_empty() {
+        trace!(
+            "window {} (app_id={:?}, title={:?}) matched no rules",
+            window.id, window.app_id, window.title
+        );
+    } else {
+        let labels: Vec<&str> = matched.iter().map(|(label, _)| label.as_str()).collect();
+        debug!(
+            "window {} (app_id={:?}, title={:?}) matched rules: {labels:?}",
+            window.id, window.app_id, window.title
+        );
+    }
+
+    matched
 }
 
-fn rule_applies(window: &Window, wr: &WindowRule) -> bool {
+fn rule_applies(window: &Window, wr: &WindowRule, workspaces: &HashMap<u64, Workspace>) -> bool {
     // probably niri has code for this that I should poach
 
-    let excludes = &wr.excludes;
-    let excluded = excludes.iter().any(|m| window_matches(window, m));
+    let excluded = wr
+        .excludes
+        .iter()
+        .any(|c| condition_matches(window, c, workspaces));
     if excluded {
         return false;
     }
 
-    let includes = &wr.matches;
-    includes.iter().any(|m| window_matches(window, m))
+    wr.conditions
+        .iter()
+        .all(|c| condition_matches(window, c, workspaces))
 }
 
-fn window_matches(window: &Window, m: &Match) -> bool {
-    // This is more complicated, I'd need to check the workspace and shit
+fn condition_matches(window: &Window, c: &Condition, workspaces: &HashMap<u64, Workspace>) -> bool {
     // Missing: active, active in column, is screencast target, on startup
-    let regex_rules = [(&m.app_id, &window.app_id), (&m.title, &window.title)]
-        .iter()
-        .filter_map(|(m, w)| {
-            let m = &m.as_ref()?.0;
-            let w = w.as_deref().unwrap_or_default();
-            Some(m.is_match(w))
-        })
-        .all(identity);
+    let matches = match c {
+        Condition::AppId(re) => re.0.is_match(window.app_id.as_deref().unwrap_or_default()),
+        Condition::Title(re) => re.0.is_match(window.title.as_deref().unwrap_or_default()),
+        Condition::IsUrgent(want) => window.is_urgent == *want,
+        Condition::IsFloating(want) => window.is_floating == *want,
+        Condition::IsFocused(want) => window.is_focused == *want,
+        Condition::Workspace(re) => workspace_of(window, workspaces)
+            .is_some_and(|ws| re.0.is_match(ws.name.as_deref().unwrap_or_default())),
+        Condition::WorkspaceIdx(idx) => {
+            workspace_of(window, workspaces).is_some_and(|ws| ws.idx == *idx)
+        }
+        Condition::WorkspaceOutput(re) => workspace_of(window, workspaces)
+            .is_some_and(|ws| re.0.is_match(ws.output.as_deref().unwrap_or_default())),
+    };
 
-    let state_rules = [m.is_urgent, m.is_floating, m.is_focused]
-        .into_iter()
-        .flatten() // a clippy suggestion down from .filter_map(identity)
-        .all(identity);
+    if !matches {
+        trace!("window {}: condition {c:?} rejected it", window.id);
+    }
+    matches
+}
 
-    regex_rules && state_rules
+fn workspace_of<'a>(window: &Window, workspaces: &'a HashMap<u64, Workspace>) -> Option<&'a Workspace> {
+    workspaces.get(&window.workspace_id?)
 }
 
+/// How long `handle_send` keeps retrying a dropped action socket before
+/// giving up on the action that triggered the reconnect. Bounded (unlike the
+/// event-reader thread's unlimited backoff) because this runs inline on the
+/// main loop and blocks it from servicing reloads/shutdown while it retries.
+const ACTION_RECONNECT_BUDGET: Duration = Duration::from_secs(10);
+
 fn handle_send(req: Request, socket: &mut Socket) -> miette::Result<()> {
-    socket.send(req.clone()).into_diagnostic()?
-    .and_then(|r| match r {
+    // The action socket has no read loop of its own to notice a dropped
+    // connection ahead of time, so reconnect-and-retry here - with a capped
+    // backoff, same idea as the event reader, so a niri restart racing with a
+    // rule consequence doesn't take the whole daemon down with it.
+    let resp = loop {
+        match socket.send(req.clone()) {
+            Ok(resp) => break resp,
+            Err(err) => {
+                warn!("sending {req:?} failed ({err}), reconnecting the action socket");
+                match reconnect_socket_with_backoff(ACTION_RECONNECT_BUDGET) {
+                    Some(reconnected) => *socket = reconnected,
+                    None => {
+                        warn!(
+                            "could not reconnect the action socket within {ACTION_RECONNECT_BUDGET:?}, dropping {req:?}"
+                        );
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    };
+    resp.and_then(|r| match r {
         Response::Handled => Ok(()),
         code => Err(
             format!("Expected niri to provide either a 'Handled' signal or an error in response to an {req:#?} request, instead got {code:#?}")
@@ -156,17 +481,77 @@ fn handle_send(req: Request, socket: &mut Socket) -> miette::Result<()> {
     Ok(())
 }
 
+/// Retries `Socket::connect` with a capped exponential backoff until it
+/// succeeds or `budget` has elapsed, in which case `None` is returned.
+fn reconnect_socket_with_backoff(budget: Duration) -> Option<Socket> {
+    let deadline = std::time::Instant::now() + budget;
+    let mut backoff = Duration::from_millis(100);
+    loop {
+        match Socket::connect() {
+            Ok(socket) => return Some(socket),
+            Err(_) if std::time::Instant::now() >= deadline => return None,
+            Err(_) => {
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(Duration::from_secs(2));
+            }
+        }
+    }
+}
+
+/// Sends `req` unless `dry_run` is set, in which case it's only logged -
+/// this is the single choke point `--dry-run` hooks into.
+fn dispatch(req: Request, socket: &mut Socket, dry_run: bool) -> miette::Result<()> {
+    if dry_run {
+        info!("[dry-run] would send {req:?}");
+        return Ok(());
+    }
+    debug!("sending {req:?}");
+    handle_send(req, socket)
+}
+
 fn take_windowrule_actions(
     window: &Window,
     windowrule: &WindowRule,
+    rule_label: &str,
+    first_match: bool,
     socket: &mut Socket,
+    supervisor: &mut Supervisor,
+    dry_run: bool,
 ) -> miette::Result<()> {
     // presumably these should also return a Handled like an EventStream
     // request but the documentation doesn't specify so I don't either
-    // NOTE: Should happen first before other rules apply, I think
-    if let Some(open_floating) = windowrule.open_floating {
-        handle_send(
-            Request::Action(match open_floating {
+    //
+    // Consequences fire in the order they're written in the config, same as
+    // herbstluftwm - so e.g. `spawn-sh` listed last still sees the window
+    // already floated/resized/moved by earlier consequences in the same rule.
+    //
+    // Placement-style consequences only fire the first time a window matches
+    // a rule - re-running them on every subsequent match would fight a user
+    // who's since floated/resized/moved the window by hand. `spawn-sh` is the
+    // exception: it needs to keep re-evaluating on every match so its
+    // on-busy policy (queue/do-nothing/restart/signal) has an actual running
+    // process to compare against instead of never firing twice.
+    for consequence in &windowrule.consequences {
+        if !first_match && !matches!(consequence, Consequence::SpawnSh { .. }) {
+            continue;
+        }
+        take_consequence(window, consequence, rule_label, socket, supervisor, dry_run)?;
+    }
+
+    Ok(())
+}
+
+fn take_consequence(
+    window: &Window,
+    consequence: &Consequence,
+    rule_label: &str,
+    socket: &mut Socket,
+    supervisor: &mut Supervisor,
+    dry_run: bool,
+) -> miette::Result<()> {
+    match consequence {
+        Consequence::Floating(floating) => dispatch(
+            Request::Action(match *floating {
                 true => Action::MoveWindowToFloating {
                     id: Some(window.id),
                 },
@@ -175,51 +560,141 @@ fn take_windowrule_actions(
                 },
             }),
             socket,
-        )?;
-    }
-
-    if let Some(DefaultPresetSize { 0: Some(change) }) = windowrule.default_window_height {
-        handle_send(
-            Request::Action(Action::SetWindowHeight {
-                id: Some(window.id),
-                change: change.into(),
+            dry_run,
+        ),
+        // No argument (`size.0 == None`) means "use the compositor default",
+        // which is a no-op here rather than an action to send.
+        Consequence::Height(size) => match size.0 {
+            None => Ok(()),
+            Some(size) => dispatch(
+                Request::Action(Action::SetWindowHeight {
+                    id: Some(window.id),
+                    change: size.into(),
+                }),
+                socket,
+                dry_run,
+            ),
+        },
+        Consequence::Width(size) => match size.0 {
+            None => Ok(()),
+            Some(size) => dispatch(
+                Request::Action(Action::SetWindowWidth {
+                    id: Some(window.id),
+                    change: size.into(),
+                }),
+                socket,
+                dry_run,
+            ),
+        },
+        Consequence::MoveToWorkspace(target) => {
+            let reference = match target.parse::<u8>() {
+                Ok(idx) => WorkspaceReferenceArg::Index(idx),
+                Err(_) => WorkspaceReferenceArg::Name(target.clone()),
+            };
+            dispatch(
+                Request::Action(Action::MoveWindowToWorkspace {
+                    window_id: Some(window.id),
+                    reference,
+                    focus: false,
+                }),
+                socket,
+                dry_run,
+            )
+        }
+        Consequence::Focus => dispatch(
+            Request::Action(Action::FocusWindow { id: window.id }),
+            socket,
+            dry_run,
+        ),
+        Consequence::Fullscreen(fullscreen) => dispatch(
+            Request::Action(Action::SetWindowFullscreen {
+                id: window.id,
+                is_fullscreen: *fullscreen,
             }),
             socket,
-        )?;
+            dry_run,
+        ),
+        Consequence::Close => dispatch(
+            Request::Action(Action::CloseWindow { id: window.id }),
+            socket,
+            dry_run,
+        ),
+        // Spawned ourselves rather than via niri's own SpawnSh action, since
+        // that hands the process off to niri and we'd never see its pid -
+        // the supervisor needs to own it to be able to stop it later.
+        Consequence::SpawnSh {
+            command,
+            on_busy,
+            stop_signal,
+            stop_timeout_secs,
+        } => {
+            let id = window.id.to_string();
+            let title = window.title.as_deref().unwrap_or_default();
+            let app_id = window.app_id.as_deref().unwrap_or_default();
+            let pid = match window.pid {
+                None => "".to_string(),
+                Some(pid) => pid.to_string(),
+            };
+            let command = command
+                .replace("{id}", &id)
+                .replace("{title}", title)
+                .replace("{app_id}", app_id)
+                .replace("{pid}", &pid);
+
+            if dry_run {
+                info!("[dry-run] would spawn {command:?} (on-busy: {on_busy:?})");
+                return Ok(());
+            }
+            supervisor.spawn(
+                window.id,
+                rule_label.to_string(),
+                command,
+                *on_busy,
+                *stop_signal,
+                Duration::from_secs_f64(stop_timeout_secs.max(0.0)),
+            );
+            Ok(())
+        }
     }
+}
 
-    if let Some(DefaultPresetSize { 0: Some(change) }) = windowrule.default_column_width {
-        handle_send(
-            Request::Action(Action::SetWindowWidth {
-                id: Some(window.id),
-                change: change.into(),
-            }),
-            socket,
-        )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(label: Option<&str>) -> WindowRule {
+        WindowRule {
+            label: label.map(str::to_string),
+            conditions: Vec::new(),
+            excludes: Vec::new(),
+            consequences: Vec::new(),
+        }
     }
 
-    // TODO: why is niri not finishing these actions before the command?
-    //       the socket is meant to be blocking isn't it?
-
-    // NOTE: Should occur last
-    if let Some(command) = &windowrule.spawn_sh {
-        let id = window.id.to_string();
-        let title = window.title.as_deref().unwrap_or_default();
-        let app_id = window.app_id.as_deref().unwrap_or_default();
-        let pid = match window.pid {
-            None => "".to_string(),
-            Some(pid) => pid.to_string(),
-        };
-        let command = command
-            .to_string()
-            .replace("{id}", &id)
-            .replace("{title}", title)
-            .replace("{app_id}", app_id)
-            .replace("{pid}", &pid);
-        let _ = socket
-            .send(Request::Action(Action::SpawnSh { command }))
-            .into_diagnostic()?;
+    #[test]
+    fn rebuild_matched_windows_carries_over_unchanged_labels() {
+        let mut old = HashMap::new();
+        old.insert("kept".to_string(), HashSet::from([1, 2]));
+        old.insert("dropped".to_string(), HashSet::from([3]));
+
+        let windowrules = vec![rule(Some("kept")), rule(Some("new"))];
+        let rebuilt = rebuild_matched_windows(old, &windowrules);
+
+        assert_eq!(rebuilt.get("kept"), Some(&HashSet::from([1, 2])));
+        assert_eq!(rebuilt.get("new"), Some(&HashSet::new()));
+        assert!(!rebuilt.contains_key("dropped"));
     }
 
-    Ok(())
+    #[test]
+    fn check_unique_labels_rejects_explicit_collisions() {
+        assert!(check_unique_labels(&[rule(Some("same")), rule(Some("same"))]).is_err());
+        assert!(check_unique_labels(&[rule(Some("a")), rule(Some("b"))]).is_ok());
+    }
+
+    #[test]
+    fn check_unique_labels_rejects_index_fallback_collisions() {
+        // An unlabeled rule at index 1 falls back to label "1", which
+        // collides with the first rule's explicit label.
+        assert!(check_unique_labels(&[rule(Some("1")), rule(None)]).is_err());
+    }
 }
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
+use crate::WindowId;
+
+/// What to do with an already-running `spawn-sh` process when the rule that
+/// launched it matches its window again, modeled on watchexec's
+/// `--on-busy-update` policies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnBusyPolicy {
+    #[default]
+    Queue,
+    DoNothing,
+    Restart,
+    Signal,
+}
+
+impl FromStr for OnBusyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queue" => Ok(OnBusyPolicy::Queue),
+            "do-nothing" => Ok(OnBusyPolicy::DoNothing),
+            "restart" => Ok(OnBusyPolicy::Restart),
+            "signal" => Ok(OnBusyPolicy::Signal),
+            other => Err(format!(
+                "unknown on-busy policy {other:?}, expected one of queue, do-nothing, restart, signal"
+            )),
+        }
+    }
+}
+
+/// A `stop-signal` value, e.g. `"SIGTERM"` or `"SIGINT"`.
+#[derive(Debug, Clone, Copy)]
+pub struct StopSignal(pub Signal);
+
+impl Default for StopSignal {
+    fn default() -> Self {
+        StopSignal(Signal::SIGTERM)
+    }
+}
+
+impl FromStr for StopSignal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Signal::from_str(&s.to_ascii_uppercase())
+            .map(StopSignal)
+            .map_err(|_| format!("unknown signal {s:?}"))
+    }
+}
+
+/// Key identifying one supervised process: the window it's tied to and the
+/// label of the rule whose `spawn-sh` consequence launched it (a rule can
+/// only have one live process per window at a time).
+type ProcessKey = (WindowId, String);
+
+struct Supervised {
+    child: Child,
+    stop_signal: Signal,
+    stop_timeout: Duration,
+    stopping_since: Option<Instant>,
+    queued_command: Option<String>,
+}
+
+impl Supervised {
+    fn still_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn begin_stop(&mut self) {
+        if self.stopping_since.is_some() {
+            return;
+        }
+        let _ = signal::kill(Pid::from_raw(self.child.id() as i32), self.stop_signal);
+        self.stopping_since = Some(Instant::now());
+    }
+
+    fn past_stop_timeout(&self) -> bool {
+        self.stopping_since
+            .is_some_and(|since| since.elapsed() >= self.stop_timeout)
+    }
+}
+
+/// Owns every process launched by a `spawn-sh` consequence so it can be torn
+/// down when its window closes instead of leaking until the daemon exits.
+#[derive(Default)]
+pub struct Supervisor {
+    processes: HashMap<ProcessKey, Supervised>,
+}
+
+impl Supervisor {
+    /// Launches `command` for `(window_id, rule_label)`, applying `policy` if
+    /// a process from a previous match of the same rule on the same window is
+    /// still alive instead of blindly spawning a duplicate.
+    pub fn spawn(
+        &mut self,
+        window_id: WindowId,
+        rule_label: String,
+        command: String,
+        policy: OnBusyPolicy,
+        stop_signal: StopSignal,
+        stop_timeout: Duration,
+    ) {
+        let key = (window_id, rule_label);
+        if let Some(existing) = self.processes.get_mut(&key) {
+            if existing.still_running() {
+                match policy {
+                    OnBusyPolicy::DoNothing => return,
+                    OnBusyPolicy::Queue => {
+                        existing.queued_command = Some(command);
+                        return;
+                    }
+                    OnBusyPolicy::Restart => {
+                        existing.queued_command = Some(command);
+                        existing.begin_stop();
+                        return;
+                    }
+                    OnBusyPolicy::Signal => {
+                        let _ = signal::kill(
+                            Pid::from_raw(existing.child.id() as i32),
+                            existing.stop_signal,
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.processes.remove(&key);
+        if let Some(child) = spawn_sh(&command) {
+            self.processes.insert(
+                key,
+                Supervised {
+                    child,
+                    stop_signal: stop_signal.0,
+                    stop_timeout,
+                    stopping_since: None,
+                    queued_command: None,
+                },
+            );
+        }
+    }
+
+    /// Asks every process tied to `window_id` to stop, giving each its own
+    /// configured grace period before `poll` escalates to SIGKILL.
+    pub fn stop_for_window(&mut self, window_id: WindowId) {
+        for (_, proc) in self
+            .processes
+            .iter_mut()
+            .filter(|((w, _), _)| *w == window_id)
+        {
+            proc.begin_stop();
+            // The window is gone for good - don't let a command queued by a
+            // `queue`/`restart` on-busy policy respawn once this process
+            // exits, there's no window left to stop it again.
+            proc.queued_command = None;
+        }
+    }
+
+    /// Stops every supervised process, blocking until they've all exited or
+    /// their stop-timeout has passed (whichever is sooner), SIGKILLing
+    /// whatever is left. Meant to be called once, on daemon shutdown.
+    pub fn shutdown(&mut self) {
+        for proc in self.processes.values_mut() {
+            proc.begin_stop();
+            // Same reasoning as `stop_for_window`: the daemon is exiting, so
+            // nothing will ever poll a queued command back to life.
+            proc.queued_command = None;
+        }
+
+        while self.processes.values_mut().any(|proc| proc.still_running()) {
+            for proc in self.processes.values_mut() {
+                if proc.still_running() && proc.past_stop_timeout() {
+                    let _ = proc.child.kill();
+                }
+            }
+            if self.processes.values_mut().all(|proc| !proc.still_running()) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Periodic upkeep: reap exited children, launch whatever they had
+    /// queued, and SIGKILL anything that ignored its stop-signal past its
+    /// stop-timeout. Call this on a timer from the main loop.
+    pub fn poll(&mut self) {
+        let mut done = Vec::new();
+        for (key, proc) in self.processes.iter_mut() {
+            match proc.child.try_wait() {
+                Ok(Some(_status)) => match proc.queued_command.take() {
+                    Some(cmd) => match spawn_sh(&cmd) {
+                        Some(child) => {
+                            proc.child = child;
+                            proc.stopping_since = None;
+                        }
+                        None => done.push(key.clone()),
+                    },
+                    None => done.push(key.clone()),
+                },
+                Ok(None) => {
+                    if proc.past_stop_timeout() {
+                        let _ = proc.child.kill();
+                    }
+                }
+                Err(_) => done.push(key.clone()),
+            }
+        }
+        for key in done {
+            self.processes.remove(&key);
+        }
+    }
+}
+
+fn spawn_sh(command: &str) -> Option<Child> {
+    match Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => Some(child),
+        Err(err) => {
+            warn!("failed to spawn {command:?}: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_busy_policy_parses_known_values() {
+        assert_eq!(OnBusyPolicy::from_str("queue").unwrap(), OnBusyPolicy::Queue);
+        assert_eq!(
+            OnBusyPolicy::from_str("do-nothing").unwrap(),
+            OnBusyPolicy::DoNothing
+        );
+        assert_eq!(
+            OnBusyPolicy::from_str("restart").unwrap(),
+            OnBusyPolicy::Restart
+        );
+        assert_eq!(
+            OnBusyPolicy::from_str("signal").unwrap(),
+            OnBusyPolicy::Signal
+        );
+        assert!(OnBusyPolicy::from_str("nonsense").is_err());
+    }
+
+    #[test]
+    fn stop_signal_parses_case_insensitively() {
+        assert_eq!(StopSignal::from_str("sigterm").unwrap().0, Signal::SIGTERM);
+        assert_eq!(StopSignal::from_str("SIGINT").unwrap().0, Signal::SIGINT);
+        assert!(StopSignal::from_str("not-a-signal").is_err());
+    }
+
+    fn rule_key(window_id: WindowId, label: &str) -> ProcessKey {
+        (window_id, label.to_string())
+    }
+
+    #[test]
+    fn do_nothing_policy_leaves_running_process_untouched_and_drops_command() {
+        let mut sup = Supervisor::default();
+        sup.spawn(
+            1,
+            "rule".to_string(),
+            "sleep 5".to_string(),
+            OnBusyPolicy::Queue,
+            StopSignal::default(),
+            Duration::from_secs(5),
+        );
+        let first_pid = sup.processes[&rule_key(1, "rule")].child.id();
+
+        sup.spawn(
+            1,
+            "rule".to_string(),
+            "echo should-not-run".to_string(),
+            OnBusyPolicy::DoNothing,
+            StopSignal::default(),
+            Duration::from_secs(5),
+        );
+
+        let proc = sup.processes.get_mut(&rule_key(1, "rule")).unwrap();
+        assert_eq!(proc.child.id(), first_pid);
+        assert!(proc.queued_command.is_none());
+        let _ = proc.child.kill();
+    }
+
+    #[test]
+    fn queue_policy_keeps_running_process_and_stashes_command_for_poll() {
+        let mut sup = Supervisor::default();
+        sup.spawn(
+            1,
+            "rule".to_string(),
+            "sleep 5".to_string(),
+            OnBusyPolicy::Queue,
+            StopSignal::default(),
+            Duration::from_secs(5),
+        );
+        let first_pid = sup.processes[&rule_key(1, "rule")].child.id();
+
+        sup.spawn(
+            1,
+            "rule".to_string(),
+            "echo queued".to_string(),
+            OnBusyPolicy::Queue,
+            StopSignal::default(),
+            Duration::from_secs(5),
+        );
+
+        let proc = sup.processes.get_mut(&rule_key(1, "rule")).unwrap();
+        assert_eq!(proc.child.id(), first_pid);
+        assert_eq!(proc.queued_command.as_deref(), Some("echo queued"));
+        let _ = proc.child.kill();
+    }
+
+    #[test]
+    fn stop_for_window_clears_any_queued_command() {
+        let mut sup = Supervisor::default();
+        sup.spawn(
+            1,
+            "rule".to_string(),
+            "sleep 5".to_string(),
+            OnBusyPolicy::Queue,
+            StopSignal::default(),
+            Duration::from_secs(5),
+        );
+        sup.spawn(
+            1,
+            "rule".to_string(),
+            "echo queued".to_string(),
+            OnBusyPolicy::Queue,
+            StopSignal::default(),
+            Duration::from_secs(5),
+        );
+
+        sup.stop_for_window(1);
+
+        let proc = sup.processes.get_mut(&rule_key(1, "rule")).unwrap();
+        assert!(proc.queued_command.is_none());
+        let _ = proc.child.kill();
+    }
+}
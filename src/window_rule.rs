@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+
+use knuffel::Decode;
+use regex::Regex;
+
+use crate::kdl_utils::DefaultPresetSize;
+use crate::supervisor::{OnBusyPolicy, StopSignal};
+
+#[derive(Debug, Decode)]
+pub struct WindowRules {
+    #[knuffel(children(name = "window-rule"))]
+    pub windowrules: Vec<WindowRule>,
+}
+
+/// A herbstluftwm-style rule: a flat list of `conditions` (all must match,
+/// i.e. AND), an optional `exclude` list (any match disqualifies the window,
+/// i.e. OR), and an ordered list of `consequences` applied in the order
+/// they're written.
+#[derive(Debug, Decode)]
+pub struct WindowRule {
+    // Stable key for `matched_windows` so a rule survives being reordered or
+    // having neighbours added/removed across a config reload. Falls back to
+    // the rule's position in the file when unset - see `label_or_index`.
+    #[knuffel(property)]
+    pub label: Option<String>,
+
+    #[knuffel(children)]
+    pub conditions: Vec<Condition>,
+    #[knuffel(children(name = "exclude"))]
+    pub excludes: Vec<Condition>,
+
+    #[knuffel(children)]
+    pub consequences: Vec<Consequence>,
+}
+
+impl WindowRule {
+    pub fn label_or_index(&self, idx: usize) -> String {
+        self.label.clone().unwrap_or_else(|| idx.to_string())
+    }
+}
+
+#[derive(Debug, Decode)]
+pub enum Condition {
+    AppId(#[knuffel(argument, str)] MatchRegex),
+    Title(#[knuffel(argument, str)] MatchRegex),
+    IsUrgent(#[knuffel(argument)] bool),
+    IsFloating(#[knuffel(argument)] bool),
+    IsFocused(#[knuffel(argument)] bool),
+    // Matched against whatever live Workspace the window currently sits on,
+    // not against the window itself.
+    Workspace(#[knuffel(argument, str)] MatchRegex),
+    WorkspaceIdx(#[knuffel(argument)] u8),
+    WorkspaceOutput(#[knuffel(argument, str)] MatchRegex),
+}
+
+#[derive(Debug, Decode)]
+pub enum Consequence {
+    Floating(#[knuffel(argument)] bool),
+    // No argument means "use the compositor default".
+    Height(DefaultPresetSize),
+    Width(DefaultPresetSize),
+    MoveToWorkspace(#[knuffel(argument, str)] String),
+    Focus,
+    Fullscreen(#[knuffel(argument)] bool),
+    Close,
+    SpawnSh {
+        #[knuffel(argument)]
+        command: String,
+        // Policy applied when this consequence fires again for a window
+        // that still has a previous invocation of it running.
+        #[knuffel(property, str, default)]
+        on_busy: OnBusyPolicy,
+        #[knuffel(property, str, default)]
+        stop_signal: StopSignal,
+        #[knuffel(property, default = 5.0)]
+        stop_timeout_secs: f64,
+    },
+}
+
+#[derive(Clone)]
+pub struct MatchRegex(pub Regex);
+
+impl fmt::Debug for MatchRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MatchRegex({:?})", self.0.as_str())
+    }
+}
+
+impl FromStr for MatchRegex {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(MatchRegex(Regex::new(s)?))
+    }
+}